@@ -3,9 +3,10 @@
 // For the full copyright and license information, please view the LICENSE
 // file that was distributed with this source code.
 //! Take all but the last elements of an iterator.
-use memchr::memchr_iter;
+use memchr::{memchr_iter, memmem};
 use std::collections::VecDeque;
-use std::io::{ErrorKind, Read, Write};
+use std::fs::File;
+use std::io::{self, ErrorKind, IoSlice, Read, Seek, SeekFrom, Write};
 
 const BUF_SIZE: usize = 65536;
 
@@ -16,17 +17,35 @@ struct TakeAllBuffer {
 
 impl TakeAllBuffer {
     fn new() -> Self {
+        // Zero-fill the backing allocation exactly once so that every byte up to
+        // `BUF_SIZE` is genuinely initialized. `Vec::with_capacity` alone would leave
+        // the spare capacity uninitialized, and handing `Read::read` a `&mut [u8]`
+        // that points at uninitialized memory is unsound even if nothing ever reads
+        // it back (this is the exact hazard `BorrowedBuf`/`Read::read_buf` close, but
+        // those are still nightly-only). Since `TakeAllBuffer`s are drawn from a pool
+        // and reused across many `fill_buffer` calls, this cost is paid once per
+        // buffer, not once per fill.
+        let mut buffer = Vec::with_capacity(BUF_SIZE);
+        buffer.resize(BUF_SIZE, 0);
+        buffer.truncate(0);
         TakeAllBuffer {
-            buffer: vec![],
+            buffer,
             start_index: 0,
         }
     }
 
     fn fill_buffer(&mut self, reader: &mut impl Read) -> std::io::Result<usize> {
-        self.buffer.resize(BUF_SIZE, 0);
         self.start_index = 0;
+        // SAFETY: `new()` zero-filled exactly the first `BUF_SIZE` bytes of this
+        // allocation, and the buffer is never reallocated to a smaller capacity, so
+        // all `BUF_SIZE` bytes are still initialized `u8` values (possibly stale data
+        // left over from a previous fill, which is fine: we only report the prefix
+        // `read` tells us it overwrote). We deliberately use `BUF_SIZE` here rather
+        // than `self.buffer.capacity()`, since the allocator may have rounded the
+        // capacity up past what `new()` actually initialized.
+        unsafe { self.buffer.set_len(BUF_SIZE) };
         loop {
-            match reader.read(&mut self.buffer[..]) {
+            match reader.read(&mut self.buffer) {
                 Ok(n) => {
                     self.buffer.truncate(n);
                     return Ok(n);
@@ -69,11 +88,62 @@ impl TakeAllBuffer {
     }
 }
 
+/// Write exactly `bytes_to_write` bytes, drawn from the front of `buffers`, in as few
+/// vectored writes as possible. Advances each buffer's cursor by whatever share of it
+/// was actually written on a short write; the caller is responsible for popping any
+/// buffers this empties out.
+fn write_vectored_exact(
+    buffers: &mut VecDeque<TakeAllBuffer>,
+    writer: &mut impl Write,
+    bytes_to_write: usize,
+) -> std::io::Result<usize> {
+    let mut written_total = 0;
+    while written_total < bytes_to_write {
+        let mut remaining = bytes_to_write - written_total;
+        let mut slices = Vec::new();
+        for buffer in buffers.iter() {
+            if remaining == 0 {
+                break;
+            }
+            let buf = buffer.remaining_buffer();
+            let take = buf.len().min(remaining);
+            slices.push(IoSlice::new(&buf[..take]));
+            remaining -= take;
+        }
+        let n = writer.write_vectored(&slices)?;
+        if n == 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::WriteZero,
+                "failed to write whole buffer",
+            ));
+        }
+        written_total += n;
+        let mut to_advance = n;
+        for buffer in buffers.iter_mut() {
+            if to_advance == 0 {
+                break;
+            }
+            let advance = buffer.remaining_bytes().min(to_advance);
+            buffer.start_index += advance;
+            to_advance -= advance;
+        }
+    }
+    Ok(written_total)
+}
+
 pub fn copy_all_but_bytes(
     reader: &mut impl Read,
     writer: &mut impl Write,
     n: usize,
 ) -> std::io::Result<usize> {
+    if n == 0 {
+        // Nothing is held back, so this is just a full passthrough. Route it through
+        // `io::copy`, which on Linux specializes to `copy_file_range`/`sendfile`/
+        // `splice` for fd-backed readers and writers instead of bouncing every byte
+        // through our own `Vec`-backed buffers.
+        return Ok(io::copy(reader, writer)? as usize);
+    }
+
     let mut buffers: VecDeque<TakeAllBuffer> = VecDeque::new();
     let mut empty_buffer_pool: Vec<TakeAllBuffer> = vec![];
     let mut buffered_bytes: usize = 0;
@@ -104,22 +174,140 @@ pub fn copy_all_but_bytes(
 
         let excess_buffered_bytes = buffered_bytes - n;
         // Since we have some data buffered, can assume we have >=1 buffer - i.e. safe to unwrap.
-        let front_buffer = buffers.front_mut().unwrap();
-        let bytes_written = front_buffer.write_bytes(writer, excess_buffered_bytes)?;
+        let bytes_written = if writer.is_write_vectored() && buffers.len() > 1 {
+            // More than one buffer is queued; write whatever of them is eligible to
+            // flush in a single vectored write instead of the front buffer alone.
+            write_vectored_exact(&mut buffers, writer, excess_buffered_bytes)?
+        } else {
+            buffers
+                .front_mut()
+                .unwrap()
+                .write_bytes(writer, excess_buffered_bytes)?
+        };
         buffered_bytes -= bytes_written;
         total_bytes_coppied += bytes_written;
-        // If the front buffer is empty (which it probably is), push it into the empty-buffer-pool.
-        if front_buffer.is_empty() {
+        // Push any now-empty buffers (likely the front one, possibly more after a
+        // vectored write) into the empty-buffer-pool.
+        while matches!(buffers.front(), Some(front) if front.is_empty()) {
             empty_buffer_pool.push(buffers.pop_front().unwrap());
         }
     }
     Ok(total_bytes_coppied)
 }
 
+/// Whether a seekable reader's length can be trusted as a fixed byte count, rather
+/// than something that can change while we're reading it or that isn't meaningfully
+/// seekable in the first place. A plain file length is a real snapshot; a pipe or
+/// FIFO can *support* `Seek` on some platforms without that length meaning anything,
+/// and a growing file's length could change between the two `seek` calls below and
+/// the actual copy.
+trait TrustedLen: Seek {
+    fn has_trusted_len(&self) -> bool;
+}
+
+impl TrustedLen for File {
+    fn has_trusted_len(&self) -> bool {
+        // NOTE: this only rules out pipes/FIFOs/character devices etc. A regular
+        // file that's actively being appended to (e.g. a live log) still passes
+        // `is_file()`, so the fast path below snapshots its length once via `seek`
+        // and copies relative to that snapshot; bytes appended after the snapshot
+        // but before the copy finishes won't be reflected in the output, which can
+        // differ from the streaming implementation's behavior on a growing file.
+        // Closing that window fully would mean re-reading during the copy, which
+        // defeats the point of the fast path, so it's accepted as a known
+        // limitation rather than guarded against here.
+        self.metadata()
+            .map(|metadata| metadata.file_type().is_file())
+            .unwrap_or(false)
+    }
+}
+
+impl<T: AsRef<[u8]>> TrustedLen for std::io::Cursor<T> {
+    fn has_trusted_len(&self) -> bool {
+        true
+    }
+}
+
+/// Like [`copy_all_but_bytes`], but for a seekable `reader`: when the input is a
+/// regular file the number of bytes to emit is simply `file_len - n`, so we can skip
+/// the ring-buffer machinery entirely and stream the prefix straight through.
+///
+/// Falls back to [`copy_all_but_bytes`] when `reader`'s length can't be trusted (a
+/// pipe, or anything else that isn't a genuine regular file).
+///
+/// Known limitation: for a regular file that's growing concurrently (e.g. a live
+/// log being appended to), this still takes the fast path and copies relative to a
+/// length snapshot taken once up front, rather than tracking growth the way the
+/// streaming implementation naturally does. See [`TrustedLen`] for why this isn't
+/// guarded against.
+pub fn copy_all_but_bytes_seek<R: Read + Seek + TrustedLen>(
+    mut reader: R,
+    writer: &mut impl Write,
+    n: usize,
+) -> std::io::Result<usize> {
+    if !reader.has_trusted_len() {
+        return copy_all_but_bytes(&mut reader, writer, n);
+    }
+
+    let len = match (|| -> std::io::Result<u64> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        Ok(len)
+    })() {
+        Ok(len) => len,
+        Err(_) => return copy_all_but_bytes(&mut reader, writer, n),
+    };
+
+    let to_copy = len.saturating_sub(n as u64);
+    let copied = io::copy(&mut reader.take(to_copy), writer)?;
+    Ok(copied as usize)
+}
+
+/// Count occurrences of `separator` across `carry` followed by `buf`. `carry` is the
+/// trailing `separator.len() - 1` bytes of whatever buffer preceded `buf`, so that a
+/// separator split across a buffer refill is still found (and counted exactly once,
+/// since `carry` alone is always too short to contain a whole separator).
+fn count_separators(carry: &[u8], buf: &[u8], separator: &[u8]) -> usize {
+    if separator.len() == 1 {
+        return memchr_iter(separator[0], buf).count();
+    }
+    if carry.is_empty() {
+        return memmem::find_iter(buf, separator).count();
+    }
+    let mut combined = Vec::with_capacity(carry.len() + buf.len());
+    combined.extend_from_slice(carry);
+    combined.extend_from_slice(buf);
+    memmem::find_iter(&combined, separator).count()
+}
+
+/// Find the end offset (exclusive, relative to the start of `buf`) of the `n`th
+/// (0-indexed) occurrence of `separator` across `carry` followed by `buf`. See
+/// [`count_separators`] for what `carry` is.
+fn nth_separator_end(carry: &[u8], buf: &[u8], separator: &[u8], n: usize) -> Option<usize> {
+    if separator.len() == 1 {
+        return memchr_iter(separator[0], buf).nth(n).map(|i| i + 1);
+    }
+    if carry.is_empty() {
+        return memmem::find_iter(buf, separator)
+            .nth(n)
+            .map(|i| i + separator.len());
+    }
+    let mut combined = Vec::with_capacity(carry.len() + buf.len());
+    combined.extend_from_slice(carry);
+    combined.extend_from_slice(buf);
+    let match_start = memmem::find_iter(&combined, separator).nth(n)?;
+    Some(match_start + separator.len() - carry.len())
+}
+
 struct TakeAllLinesBuffer {
     // Todo - rename buffer -> inner
     buffer: TakeAllBuffer,
     lines: usize,
+    /// The trailing `separator.len() - 1` bytes of the buffer that preceded this one,
+    /// kept around in case a separator is split across the refill boundary. Only
+    /// meaningful while `buffer.start_index == 0`; once we've started writing out of
+    /// this buffer, that boundary is behind us.
+    carry: Vec<u8>,
 }
 
 struct BytesAndLines {
@@ -132,46 +320,56 @@ impl TakeAllLinesBuffer {
         TakeAllLinesBuffer {
             buffer: TakeAllBuffer::new(),
             lines: 0,
+            carry: Vec::new(),
         }
     }
 
     fn fill_buffer(
         &mut self,
         reader: &mut impl Read,
-        separator: u8,
+        separator: &[u8],
+        carry: &[u8],
     ) -> std::io::Result<BytesAndLines> {
         let bytes_read = self.buffer.fill_buffer(reader)?;
-        // Count the number of lines...
-        self.lines = memchr_iter(separator, self.buffer.remaining_buffer()).count();
+        self.carry.clear();
+        self.carry.extend_from_slice(carry);
+        self.lines = count_separators(&self.carry, self.buffer.remaining_buffer(), separator);
         Ok(BytesAndLines {
             bytes: bytes_read,
             lines: self.lines,
         })
     }
 
+    /// The trailing `len` bytes of this buffer, to hand the next buffer as its `carry`.
+    fn trailing_bytes(&self, len: usize) -> Vec<u8> {
+        let buf = self.buffer.remaining_buffer();
+        buf[buf.len().saturating_sub(len)..].to_vec()
+    }
+
     fn do_write_lines(
         &mut self,
         writer: &mut impl Write,
         max_lines: usize,
-        separator: u8,
+        separator: &[u8],
     ) -> std::io::Result<usize> {
-        let index = memchr_iter(separator, self.buffer.remaining_buffer()).nth(max_lines - 1);
-        assert!(
-            index.is_some(),
-            "Somehow we're being asked to write more lines than we have, that's a bug in the client."
-        );
-        let index = index.unwrap();
-        // index is the offset of the separator character, zero indexed. Need to add 1 to get the number
-        // of bytes to write.
-        self.buffer.write_bytes_exact(writer, index + 1)?;
-        Ok(index + 1)
+        let carry: &[u8] = if self.buffer.start_index == 0 {
+            &self.carry
+        } else {
+            &[]
+        };
+        let end = nth_separator_end(carry, self.buffer.remaining_buffer(), separator, max_lines - 1)
+            .expect(
+                "Somehow we're being asked to write more lines than we have, that's a bug in the client.",
+            );
+        self.buffer.write_bytes_exact(writer, end)?;
+        Ok(end)
     }
 
     fn write_lines(
         &mut self,
         writer: &mut impl Write,
         max_lines: usize,
-        separator: u8,
+        separator: &[u8],
     ) -> std::io::Result<BytesAndLines> {
         assert!(max_lines > 0, "Must request at least 1 line.");
         let ret;
@@ -204,16 +402,125 @@ impl TakeAllLinesBuffer {
     }
 }
 
+/// Like [`write_vectored_exact`], but for [`TakeAllLinesBuffer`]s: writes exactly the
+/// bytes that make up `max_lines` lines, drawn from the front of `buffers`, in as few
+/// vectored writes as possible.
+fn write_lines_vectored(
+    buffers: &mut VecDeque<TakeAllLinesBuffer>,
+    writer: &mut impl Write,
+    max_lines: usize,
+    separator: &[u8],
+) -> std::io::Result<BytesAndLines> {
+    // Work out how many bytes, spanning as many buffers as needed, make up exactly
+    // `max_lines` lines.
+    let mut lines_left = max_lines;
+    let mut bytes_to_write = 0;
+    for buffer in buffers.iter() {
+        if lines_left == 0 {
+            break;
+        }
+        if lines_left > buffer.lines() {
+            bytes_to_write += buffer.remaining_bytes();
+            lines_left -= buffer.lines();
+        } else {
+            let carry: &[u8] = if buffer.buffer.start_index == 0 {
+                &buffer.carry
+            } else {
+                &[]
+            };
+            let end = nth_separator_end(
+                carry,
+                buffer.buffer.remaining_buffer(),
+                separator,
+                lines_left - 1,
+            )
+            .expect(
+                "Somehow we're being asked to write more lines than we have, that's a bug in the client.",
+            );
+            bytes_to_write += end;
+            lines_left = 0;
+        }
+    }
+
+    let mut written_total = 0;
+    while written_total < bytes_to_write {
+        let mut remaining = bytes_to_write - written_total;
+        let mut slices = Vec::new();
+        for buffer in buffers.iter() {
+            if remaining == 0 {
+                break;
+            }
+            let buf = buffer.buffer.remaining_buffer();
+            let take = buf.len().min(remaining);
+            slices.push(IoSlice::new(&buf[..take]));
+            remaining -= take;
+        }
+        let n = writer.write_vectored(&slices)?;
+        if n == 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::WriteZero,
+                "failed to write whole buffer",
+            ));
+        }
+        written_total += n;
+        let mut to_advance = n;
+        for buffer in buffers.iter_mut() {
+            if to_advance == 0 {
+                break;
+            }
+            let advance = buffer.remaining_bytes().min(to_advance);
+            buffer.buffer.start_index += advance;
+            to_advance -= advance;
+        }
+    }
+
+    // Every buffer's cursor has now moved past the lines it contributed; recompute
+    // each one's remaining line count rather than tracking it through the writes
+    // above. A buffer that wasn't touched at all still needs its own `carry` folded
+    // in so this matches the count `fill_buffer` originally produced for it.
+    let mut lines_written = 0;
+    for buffer in buffers.iter_mut() {
+        let carry: &[u8] = if buffer.buffer.start_index == 0 {
+            &buffer.carry
+        } else {
+            &[]
+        };
+        let remaining_lines = count_separators(carry, buffer.buffer.remaining_buffer(), separator);
+        let consumed_lines = buffer.lines - remaining_lines;
+        if consumed_lines == 0 {
+            continue;
+        }
+        buffer.lines = remaining_lines;
+        lines_written += consumed_lines;
+    }
+
+    Ok(BytesAndLines {
+        bytes: written_total,
+        lines: lines_written,
+    })
+}
+
 pub fn copy_all_but_lines<R: Read, W: Write>(
     mut reader: R,
     writer: &mut W,
     n: usize,
-    separator: u8,
+    separator: &[u8],
 ) -> std::io::Result<usize> {
+    if n == 0 {
+        // Nothing is held back, so this is just a full passthrough. Route it through
+        // `io::copy`, which on Linux specializes to `copy_file_range`/`sendfile`/
+        // `splice` for fd-backed readers and writers instead of bouncing every byte
+        // through our own `Vec`-backed buffers.
+        return Ok(io::copy(&mut reader, writer)? as usize);
+    }
+
     let mut buffers: VecDeque<TakeAllLinesBuffer> = VecDeque::new();
     let mut buffered_lines: usize = 0;
     let mut empty_buffers = vec![];
     let mut total_bytes_coppied = 0;
+    // The trailing `separator.len() - 1` bytes of the most recently filled buffer, in
+    // case a multi-byte separator straddles the refill boundary.
+    let mut carry: Vec<u8> = Vec::new();
     loop {
         // Try to buffer enough such that we can write out the entire first buffer.
         loop {
@@ -227,11 +534,12 @@ pub fn copy_all_but_lines<R: Read, W: Write>(
             }
             // We need to try to buffer more data...
             let mut new_buffer = empty_buffers.pop().unwrap_or_else(TakeAllLinesBuffer::new);
-            let fill_result = new_buffer.fill_buffer(&mut reader, separator)?;
+            let fill_result = new_buffer.fill_buffer(&mut reader, separator, &carry)?;
             if fill_result.bytes == 0 {
                 // Must have hit EoF
                 break;
             }
+            carry = new_buffer.trailing_bytes(separator.len().saturating_sub(1));
             buffered_lines += fill_result.lines;
             buffers.push_back(new_buffer);
         }
@@ -242,13 +550,22 @@ pub fn copy_all_but_lines<R: Read, W: Write>(
         }
 
         // Since we have some data buffered, can assume we have at least 1 bufffer.
-        let front_buffer = buffers.front_mut().unwrap();
         let excess_buffered_lines = buffered_lines - n;
-        let write_result = front_buffer.write_lines(writer, excess_buffered_lines, separator)?;
+        let write_result = if writer.is_write_vectored() && buffers.len() > 1 {
+            // More than one buffer is queued; write whatever of them is eligible to
+            // flush in a single vectored write instead of the front buffer alone.
+            write_lines_vectored(&mut buffers, writer, excess_buffered_lines, separator)?
+        } else {
+            buffers
+                .front_mut()
+                .unwrap()
+                .write_lines(writer, excess_buffered_lines, separator)?
+        };
         buffered_lines -= write_result.lines;
         total_bytes_coppied += write_result.bytes;
-        // If the front buffer is empty (which it probably is), push it into the empty-buffer-pool.
-        if front_buffer.is_empty() {
+        // Push any now-empty buffers (likely the front one, possibly more after a
+        // vectored write) into the empty-buffer-pool.
+        while matches!(buffers.front(), Some(front) if front.is_empty()) {
             empty_buffers.push(buffers.pop_front().unwrap());
         }
     }
@@ -263,7 +580,11 @@ pub fn copy_all_but_lines<R: Read, W: Write>(
 pub struct TakeLines<T> {
     inner: T,
     limit: u64,
-    separator: u8,
+    separator: Vec<u8>,
+    /// The trailing `separator.len() - 1` bytes handed back to the caller on a
+    /// previous call to `read`, kept around only so a separator split across two
+    /// `read` calls is still recognized; never re-reported as newly read.
+    carry: Vec<u8>,
 }
 
 impl<T: Read> Read for TakeLines<T> {
@@ -275,12 +596,37 @@ impl<T: Read> Read for TakeLines<T> {
         match self.inner.read(buf) {
             Ok(0) => Ok(0),
             Ok(n) => {
-                for i in memchr_iter(self.separator, &buf[..n]) {
+                if let [separator] = self.separator[..] {
+                    // Fast path: a single-byte separator can't straddle two calls.
+                    for i in memchr_iter(separator, &buf[..n]) {
+                        self.limit -= 1;
+                        if self.limit == 0 {
+                            return Ok(i + 1);
+                        }
+                    }
+                    return Ok(n);
+                }
+
+                let old_carry_len = self.carry.len();
+                self.carry.extend_from_slice(&buf[..n]);
+                for match_start in memmem::find_iter(&self.carry, &self.separator[..]) {
                     self.limit -= 1;
                     if self.limit == 0 {
-                        return Ok(i + 1);
+                        let end = match_start + self.separator.len();
+                        // Only report bytes from this call's `buf` as consumed;
+                        // anything before that was already returned to the caller.
+                        let consumed = end - old_carry_len;
+                        self.carry.clear();
+                        return Ok(consumed);
                     }
                 }
+                // Keep only the trailing bytes that could still start a split
+                // separator; the rest has been scanned and won't be looked at again.
+                let keep_from = self
+                    .carry
+                    .len()
+                    .saturating_sub(self.separator.len().saturating_sub(1));
+                self.carry.drain(..keep_from);
                 Ok(n)
             }
             Err(e) => Err(e),
@@ -297,21 +643,115 @@ impl<T: Read> Read for TakeLines<T> {
 /// The `separator` defines the character to interpret as the line
 /// ending. For the usual notion of "line", set this to `b'\n'`.
 pub fn take_lines<R>(reader: R, limit: u64, separator: u8) -> TakeLines<R> {
+    take_lines_with(reader, limit, &[separator])
+}
+
+/// Like [`take_lines`], but `separator` may be an arbitrary byte string (e.g. `b"\r\n"`
+/// or a longer record delimiter) instead of a single byte.
+pub fn take_lines_with<R>(reader: R, limit: u64, separator: &[u8]) -> TakeLines<R> {
     TakeLines {
         inner: reader,
         limit,
-        separator,
+        separator: separator.to_vec(),
+        carry: Vec::new(),
     }
 }
 
+/// Copy the first `limit` lines from `reader` to `writer`.
+///
+/// This is the `head -n N` case: the prefix to copy is determined on the fly by
+/// [`TakeLines`] as bytes are read. `TakeLines` is our own adapter type, which is
+/// invisible to `std::io::copy`'s specialization (keyed to concrete types like
+/// `File`, not arbitrary wrappers), so this always falls back to the generic
+/// buffered-copy loop. Use [`copy_lines_seek`] instead when `reader` is seekable, to
+/// get the same `copy_file_range`/`sendfile`/`splice` fast path that
+/// [`copy_all_but_bytes_seek`] gets from wrapping in `std::io::Take`.
+pub fn copy_lines<R: Read, W: Write>(
+    reader: R,
+    writer: &mut W,
+    limit: u64,
+    separator: u8,
+) -> std::io::Result<u64> {
+    io::copy(&mut take_lines(reader, limit, separator), writer)
+}
+
+/// Like [`copy_lines`], but for a seekable `reader`: scans ahead to find the exact
+/// byte length of the first `limit` lines, then copies that span via `reader.take(len)`
+/// (`std`'s own [`Take`](std::io::Take)) instead of wrapping `reader` in `TakeLines`.
+/// Because `io::copy`'s specialization is keyed to concrete types, staying in `std`'s
+/// own `Take<R>` lets it specialize to `copy_file_range`/`sendfile`/`splice` for
+/// fd-backed readers and writers on Linux, where [`copy_lines`] cannot.
+///
+/// Falls back to [`copy_lines`] when `reader`'s length can't be trusted (a pipe, or
+/// anything else that isn't a genuine regular file); the scan itself never runs in
+/// that case, so there's no risk of double-reading.
+pub fn copy_lines_seek<R: Read + Seek + TrustedLen>(
+    mut reader: R,
+    writer: &mut impl Write,
+    limit: u64,
+    separator: u8,
+) -> std::io::Result<u64> {
+    if !reader.has_trusted_len() {
+        return copy_lines(&mut reader, writer, limit, separator);
+    }
+
+    let prefix_len = count_prefix_len(&mut reader, limit, separator)?;
+    io::copy(&mut reader.take(prefix_len), writer)
+}
+
+/// Scan `reader` from its current position for the byte length spanning its first
+/// `limit` lines (or its whole remaining content, if it has fewer), then seek back to
+/// where it started.
+fn count_prefix_len<R: Read + Seek>(
+    reader: &mut R,
+    limit: u64,
+    separator: u8,
+) -> std::io::Result<u64> {
+    let start = reader.stream_position()?;
+    let mut remaining = limit;
+    let mut total: u64 = 0;
+    let mut buf = vec![0u8; BUF_SIZE];
+    while remaining > 0 {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        let mut found_end = None;
+        for i in memchr_iter(separator, &buf[..n]) {
+            remaining -= 1;
+            if remaining == 0 {
+                found_end = Some(i + 1);
+                break;
+            }
+        }
+        match found_end {
+            Some(end) => {
+                total += end as u64;
+                break;
+            }
+            None => total += n as u64,
+        }
+    }
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(total)
+}
+
 #[cfg(test)]
 mod tests {
 
+    use std::collections::VecDeque;
     use std::io::BufRead;
     use std::io::BufReader;
+    use std::io::Write;
 
     use crate::take::take_all_but;
     use crate::take::take_lines;
+    use crate::take::take_lines_with;
+
+    use super::{
+        copy_all_but_bytes_seek, copy_lines_seek, write_lines_vectored, write_vectored_exact,
+        TakeAllBuffer, TakeAllLinesBuffer, TrustedLen,
+    };
 
     #[test]
     fn test_fewer_elements() {
@@ -369,4 +809,186 @@ mod tests {
         assert_eq!(Some(String::from("c")), iter.next());
         assert_eq!(None, iter.next());
     }
+
+    #[test]
+    fn test_multi_byte_separator() {
+        let input_reader = std::io::Cursor::new("a\r\nb\r\nc\r\n");
+        let output_reader = BufReader::new(take_lines_with(input_reader, 2, b"\r\n"));
+        let mut iter = output_reader.split(b'\n').map(|l| l.unwrap());
+        assert_eq!(Some(b"a\r".to_vec()), iter.next());
+        assert_eq!(Some(b"b\r".to_vec()), iter.next());
+        assert_eq!(None, iter.next());
+    }
+
+    #[test]
+    fn test_multi_byte_separator_split_across_reads() {
+        // Force tiny reads so the "\r\n" separator between the 2nd and 3rd line is
+        // split across two calls to `TakeLines::read`.
+        struct OneByteAtATime<R>(R);
+        impl<R: std::io::Read> std::io::Read for OneByteAtATime<R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.0.read(&mut buf[..1.min(buf.len())])
+            }
+        }
+
+        let input_reader = OneByteAtATime(std::io::Cursor::new("a\r\nb\r\nc\r\n"));
+        let output_reader = BufReader::new(take_lines_with(input_reader, 2, b"\r\n"));
+        let mut iter = output_reader.split(b'\n').map(|l| l.unwrap());
+        assert_eq!(Some(b"a\r".to_vec()), iter.next());
+        assert_eq!(Some(b"b\r".to_vec()), iter.next());
+        assert_eq!(None, iter.next());
+    }
+
+    /// A [`Write`] that only ever accepts `max_per_call` bytes per `write`/
+    /// `write_vectored` call, to exercise the partial-advance bookkeeping in
+    /// `write_vectored_exact`/`write_lines_vectored`.
+    struct ShortWriter {
+        data: Vec<u8>,
+        max_per_call: usize,
+    }
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.max_per_call);
+            self.data.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
+            let mut written = 0;
+            for buf in bufs {
+                if written >= self.max_per_call {
+                    break;
+                }
+                let take = buf.len().min(self.max_per_call - written);
+                self.data.extend_from_slice(&buf[..take]);
+                written += take;
+            }
+            Ok(written)
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            true
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_vectored_exact_handles_short_writes() {
+        let mut buffers: VecDeque<TakeAllBuffer> = VecDeque::new();
+        for chunk in [&b"hello "[..], &b"world!"[..]] {
+            let mut buf = TakeAllBuffer::new();
+            buf.fill_buffer(&mut std::io::Cursor::new(chunk.to_vec()))
+                .unwrap();
+            buffers.push_back(buf);
+        }
+        let mut writer = ShortWriter {
+            data: Vec::new(),
+            max_per_call: 4,
+        };
+        let total = b"hello world!".len();
+        let written = write_vectored_exact(&mut buffers, &mut writer, total).unwrap();
+        assert_eq!(written, total);
+        assert_eq!(writer.data, b"hello world!");
+    }
+
+    #[test]
+    fn test_write_lines_vectored_handles_short_writes_across_buffers() {
+        let mut buffers: VecDeque<TakeAllLinesBuffer> = VecDeque::new();
+        for chunk in ["a\nb\n", "c\nd\n"] {
+            let mut buf = TakeAllLinesBuffer::new();
+            buf.fill_buffer(&mut std::io::Cursor::new(chunk.as_bytes().to_vec()), b"\n", &[])
+                .unwrap();
+            buffers.push_back(buf);
+        }
+        let mut writer = ShortWriter {
+            data: Vec::new(),
+            max_per_call: 3,
+        };
+        // Asking for 3 lines spans both buffers: "a\nb\n" fully, then "c\n" out of
+        // the second buffer.
+        let result = write_lines_vectored(&mut buffers, &mut writer, 3, b"\n").unwrap();
+        assert_eq!(result.lines, 3);
+        assert_eq!(result.bytes, 6);
+        assert_eq!(writer.data, b"a\nb\nc\n");
+    }
+
+    #[test]
+    fn test_take_all_buffer_reuse_does_not_expose_stale_bytes() {
+        let mut buffer = TakeAllBuffer::new();
+        let n1 = buffer
+            .fill_buffer(&mut std::io::Cursor::new(b"hello world".to_vec()))
+            .unwrap();
+        assert_eq!(&buffer.remaining_buffer()[..n1], b"hello world");
+
+        // Refill with fewer bytes than before. The backing allocation still holds
+        // the previous fill's bytes past `n2`, but only the first `n2` of them
+        // should ever be visible through `remaining_buffer`.
+        let n2 = buffer
+            .fill_buffer(&mut std::io::Cursor::new(b"hi".to_vec()))
+            .unwrap();
+        assert_eq!(n2, 2);
+        assert_eq!(buffer.remaining_buffer(), b"hi");
+    }
+
+    /// A `Read + Seek` wrapper that reports its length can never be trusted, to
+    /// exercise `copy_all_but_bytes_seek`'s fallback path.
+    struct UntrustedSeek<R>(R);
+
+    impl<R: std::io::Read> std::io::Read for UntrustedSeek<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl<R: std::io::Seek> std::io::Seek for UntrustedSeek<R> {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    impl<R: std::io::Seek> TrustedLen for UntrustedSeek<R> {
+        fn has_trusted_len(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_copy_all_but_bytes_seek_falls_back_when_untrusted() {
+        let input = UntrustedSeek(std::io::Cursor::new(b"hello world".to_vec()));
+        let mut output = Vec::new();
+        let copied = copy_all_but_bytes_seek(input, &mut output, 5).unwrap();
+        assert_eq!(copied, 6);
+        assert_eq!(output, b"hello ");
+    }
+
+    #[test]
+    fn test_copy_lines_seek_trusted_reader() {
+        let input = std::io::Cursor::new(b"a\nb\nc\n".to_vec());
+        let mut output = Vec::new();
+        let copied = copy_lines_seek(input, &mut output, 2, b'\n').unwrap();
+        assert_eq!(copied, 4);
+        assert_eq!(output, b"a\nb\n");
+    }
+
+    #[test]
+    fn test_copy_lines_seek_falls_back_when_untrusted() {
+        let input = UntrustedSeek(std::io::Cursor::new(b"a\nb\nc\n".to_vec()));
+        let mut output = Vec::new();
+        let copied = copy_lines_seek(input, &mut output, 2, b'\n').unwrap();
+        assert_eq!(copied, 4);
+        assert_eq!(output, b"a\nb\n");
+    }
+
+    #[test]
+    fn test_copy_lines_seek_more_lines_than_available() {
+        let input = std::io::Cursor::new(b"a\nb\nc\n".to_vec());
+        let mut output = Vec::new();
+        let copied = copy_lines_seek(input, &mut output, 10, b'\n').unwrap();
+        assert_eq!(copied, 6);
+        assert_eq!(output, b"a\nb\nc\n");
+    }
 }
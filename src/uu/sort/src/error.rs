@@ -0,0 +1,58 @@
+// This file is part of the uutils coreutils package.
+//
+// For the full copyright and license information, please view the LICENSE
+// file that was distributed with this source code.
+
+// NOTE: this snapshot of the crate doesn't include `sort.rs` (the crate root that
+// owns CLI parsing, the external-merge driver, and the rest of `SortError`'s
+// variants), so this file can't actually be wired in here via `mod error;` /
+// `pub use error::SortError;` the way it would be in the full crate. It exists so
+// the variants `tmp_dir.rs` references for `--compress-program` support
+// (`CompressProgExecutionFailed`, `CompressProgTerminatedAbnormally`) are defined
+// somewhere concrete rather than only implied by a `use crate::SortError;` that
+// nothing backs. Whoever owns `sort.rs` should fold these into the real enum
+// there alongside its other variants (`TmpFileCreationFailed`, `OpenTmpFileFailed`,
+// and whatever else `sort.rs` already defines).
+
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+use uucore::error::UError;
+
+/// Errors specific to the external sort driver and its temp-file handling.
+#[derive(Debug)]
+pub enum SortError {
+    /// Creating the temp directory used to spill sort chunks to disk failed.
+    TmpFileCreationFailed { path: PathBuf },
+    /// Opening (or creating) a temp file failed.
+    OpenTmpFileFailed { error: std::io::Error },
+    /// Spawning the `--compress-program`/`--decompress-program` process failed.
+    CompressProgExecutionFailed { error: std::io::Error },
+    /// The `--compress-program` process exited non-zero after accepting data, so the
+    /// temp file it was writing to is truncated or otherwise not trustworthy.
+    CompressProgTerminatedAbnormally { status: ExitStatus },
+}
+
+impl Display for SortError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TmpFileCreationFailed { path } => {
+                write!(f, "could not create temporary directory in {}", path.display())
+            }
+            Self::OpenTmpFileFailed { error } => {
+                write!(f, "could not open temporary file: {error}")
+            }
+            Self::CompressProgExecutionFailed { error } => {
+                write!(f, "couldn't execute compress program: {error}")
+            }
+            Self::CompressProgTerminatedAbnormally { status } => {
+                write!(f, "compress program terminated abnormally: {status}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SortError {}
+
+impl UError for SortError {}
@@ -5,7 +5,9 @@
 
 use std::{
     fs::File,
+    io::{Read, Write},
     path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
     sync::{Arc, Mutex},
 };
 
@@ -15,21 +17,27 @@ use uucore::{
     show_error,
 };
 
+// `SortError` (including the `CompressProgExecutionFailed`/`CompressProgTerminatedAbnormally`
+// variants this file relies on) is defined in `error.rs`. This crate's `Cargo.toml` also
+// needs `nix` (unix) and `windows-sys` (windows, with the `Win32_Foundation` and
+// `Win32_System_Console` features) added as dependencies for the `use`s below to resolve;
+// neither is declared in the baseline manifest.
 use crate::SortError;
 
-#[cfg(target_os = "linux")]
+#[cfg(unix)]
 use signal_hook::iterator::Handle;
-#[cfg(target_os = "linux")]
+#[cfg(unix)]
 use std::thread::JoinHandle;
 
-/// signal handler listens for SIGUSR1 signal and runs provided closure.
-#[cfg(target_os = "linux")]
+/// signal handler listens for `SIGINT`, `SIGTERM`, `SIGHUP` and `SIGQUIT` and runs
+/// the provided closure for any of them.
+#[cfg(unix)]
 pub(crate) struct SignalHandler {
     handle: Handle,
     thread: Option<JoinHandle<()>>,
 }
 
-#[cfg(target_os = "linux")]
+#[cfg(unix)]
 impl SignalHandler {
     pub(crate) fn install_signal_handler(
         f: Box<dyn Send + Sync + Fn()>,
@@ -37,12 +45,12 @@ impl SignalHandler {
         use signal_hook::consts::signal::*;
         use signal_hook::iterator::Signals;
 
-        let mut signals = Signals::new([SIGINT])?;
+        let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP, SIGQUIT])?;
         let handle = signals.handle();
         let thread = std::thread::spawn(move || {
             for signal in &mut signals {
                 match signal {
-                    SIGINT => (*f)(),
+                    SIGINT | SIGTERM | SIGHUP | SIGQUIT => (*f)(),
                     _ => unreachable!(),
                 }
             }
@@ -55,7 +63,36 @@ impl SignalHandler {
     }
 }
 
-#[cfg(target_os = "linux")]
+#[cfg(unix)]
+impl SignalHandler {
+    /// Like [`install_signal_handler`](Self::install_signal_handler), but listens for
+    /// `SIGUSR1` alone and never exits the process; intended for non-fatal progress
+    /// reporting run alongside the fatal-signal handler.
+    pub(crate) fn install_progress_handler(
+        f: Box<dyn Send + Sync + Fn()>,
+    ) -> Result<Self, std::io::Error> {
+        use signal_hook::consts::signal::SIGUSR1;
+        use signal_hook::iterator::Signals;
+
+        let mut signals = Signals::new([SIGUSR1])?;
+        let handle = signals.handle();
+        let thread = std::thread::spawn(move || {
+            for signal in &mut signals {
+                match signal {
+                    SIGUSR1 => (*f)(),
+                    _ => unreachable!(),
+                }
+            }
+        });
+
+        Ok(Self {
+            handle,
+            thread: Some(thread),
+        })
+    }
+}
+
+#[cfg(unix)]
 impl Drop for SignalHandler {
     fn drop(&mut self) {
         self.handle.close();
@@ -65,19 +102,245 @@ impl Drop for SignalHandler {
     }
 }
 
+/// Windows has no POSIX signals, so Ctrl-C/close is delivered through a console
+/// control handler instead; it runs the same cleanup closure.
+#[cfg(windows)]
+pub(crate) struct SignalHandler;
+
+#[cfg(windows)]
+impl SignalHandler {
+    pub(crate) fn install_signal_handler(
+        f: Box<dyn Send + Sync + Fn()>,
+    ) -> Result<Self, std::io::Error> {
+        use std::sync::OnceLock;
+        use windows_sys::Win32::Foundation::BOOL;
+        use windows_sys::Win32::System::Console::{
+            SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_C_EVENT,
+            CTRL_LOGOFF_EVENT, CTRL_SHUTDOWN_EVENT,
+        };
+
+        static CALLBACK: OnceLock<Box<dyn Send + Sync + Fn()>> = OnceLock::new();
+        // `TmpDirWrapper` only ever installs one of these; a second installation
+        // would otherwise leave the console handler silently running the first
+        // instance's (now stale) cleanup closure, so reject it instead.
+        if CALLBACK.set(f).is_err() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "a console control handler is already installed",
+            ));
+        }
+
+        unsafe extern "system" fn handler_routine(ctrl_type: u32) -> BOOL {
+            match ctrl_type {
+                CTRL_C_EVENT | CTRL_BREAK_EVENT | CTRL_CLOSE_EVENT | CTRL_LOGOFF_EVENT
+                | CTRL_SHUTDOWN_EVENT => {
+                    if let Some(f) = CALLBACK.get() {
+                        (*f)();
+                    }
+                    1
+                }
+                _ => 0,
+            }
+        }
+
+        if unsafe { SetConsoleCtrlHandler(Some(handler_routine), 1) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self)
+    }
+
+    /// Windows has no `SIGUSR1` equivalent, so progress reporting is a no-op here.
+    pub(crate) fn install_progress_handler(
+        _f: Box<dyn Send + Sync + Fn()>,
+    ) -> Result<Self, std::io::Error> {
+        Ok(Self)
+    }
+}
+
+#[cfg(windows)]
+impl Drop for SignalHandler {
+    fn drop(&mut self) {}
+}
+
+
+/// Spawns the external compressor/decompressor processes used by `--compress-program`.
+///
+/// Every temp file allocated through [`TmpDirWrapper::next_compressed_file`] is piped
+/// through `prog` on the way to disk, and read back through
+/// [`TmpDirWrapper::open_compressed_file`] via `decompress_prog` (or `prog -d`, if none
+/// was given); files from [`TmpDirWrapper::next_file`] are not compressed.
+#[derive(Clone)]
+pub struct CompressProg {
+    prog: String,
+    decompress_prog: Option<String>,
+}
+
+impl CompressProg {
+    pub fn new(prog: String, decompress_prog: Option<String>) -> Self {
+        Self {
+            prog,
+            decompress_prog,
+        }
+    }
+
+    fn spawn_writer(&self, file: File) -> UResult<CompressedFileWriter> {
+        let mut child = Command::new(&self.prog)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::from(file))
+            .spawn()
+            .map_err(|error| SortError::CompressProgExecutionFailed { error })?;
+        let stdin = child.stdin.take().unwrap();
+        Ok(CompressedFileWriter {
+            child,
+            stdin: Some(stdin),
+        })
+    }
+
+    fn spawn_reader(&self, path: &Path) -> UResult<CompressedFileReader> {
+        let file = File::open(path).map_err(|error| SortError::OpenTmpFileFailed { error })?;
+        let mut command = match &self.decompress_prog {
+            Some(decompress_prog) => Command::new(decompress_prog),
+            None => {
+                let mut command = Command::new(&self.prog);
+                command.arg("-d");
+                command
+            }
+        };
+        let mut child = command
+            .stdin(Stdio::from(file))
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|error| SortError::CompressProgExecutionFailed { error })?;
+        let stdout = child.stdout.take().unwrap();
+        Ok(CompressedFileReader { child, stdout })
+    }
+}
+
+/// A [`Write`] whose caller must call [`finish`](Self::finish) to learn whether the
+/// underlying sink actually succeeded, rather than relying solely on `Drop`, which
+/// can't return a [`Result`].
+pub trait FinishableWrite: Write {
+    /// Flush and close this writer, surfacing any failure that only becomes visible
+    /// once the sink is closed (e.g. a piped compressor exiting non-zero after it had
+    /// already accepted all the data).
+    fn finish(self: Box<Self>) -> UResult<()>;
+}
+
+impl FinishableWrite for File {
+    fn finish(self: Box<Self>) -> UResult<()> {
+        Ok(())
+    }
+}
+
+/// A [`Write`] that pipes everything written to it through an external compressor
+/// process before it reaches the backing temp file.
+pub struct CompressedFileWriter {
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl Write for CompressedFileWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stdin.as_mut().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stdin.as_mut().unwrap().flush()
+    }
+}
+
+impl FinishableWrite for CompressedFileWriter {
+    fn finish(mut self: Box<Self>) -> UResult<()> {
+        // Drop `stdin` first so the compressor sees EOF; only then can `wait()`
+        // observe it exit, so the pipe is flushed before we check its status.
+        drop(self.stdin.take());
+        let status = self
+            .child
+            .wait()
+            .map_err(|error| SortError::CompressProgExecutionFailed { error })?;
+        if !status.success() {
+            return Err(SortError::CompressProgTerminatedAbnormally { status }.into());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CompressedFileWriter {
+    fn drop(&mut self) {
+        // Best-effort fallback for callers that drop this writer without calling
+        // `finish` (e.g. because an earlier `write` already failed): still reap the
+        // child so it doesn't linger as a zombie, but since `Drop` can't return a
+        // `Result`, a non-zero exit here is silently lost. Callers on the success
+        // path should call `finish` instead of relying on this.
+        drop(self.stdin.take());
+        let _ = self.child.wait();
+    }
+}
+
+/// A [`Read`] that decompresses a temp file on the fly through an external
+/// decompressor process.
+pub struct CompressedFileReader {
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl Read for CompressedFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+
+impl Drop for CompressedFileReader {
+    fn drop(&mut self) {
+        let _ = self.child.wait();
+    }
+}
+
+/// Phase of the external merge, reported by the `SIGUSR1` progress handler.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MergePhase {
+    /// Reading and buffering input chunks before any temp files are written.
+    Buffering,
+    /// Merging previously-spilled temp files.
+    Merging,
+}
+
+impl MergePhase {
+    fn as_str(self) -> &'static str {
+        match self {
+            MergePhase::Buffering => "buffering input",
+            MergePhase::Merging => "merging temp files",
+        }
+    }
+}
+
+/// State read by the `SIGUSR1` progress handler and updated by the sort driver as it
+/// spills and merges chunks.
+#[derive(Default)]
+struct Status {
+    phase: Option<MergePhase>,
+    temp_files: usize,
+    spilled_bytes: u64,
+}
 
 /// A wrapper around TempDir that may only exist once in a process.
 ///
 /// `TmpDirWrapper` handles the allocation of new temporary files in this temporary directory and
-/// deleting the whole directory when `SIGINT` is received. Creating a second `TmpDirWrapper` will
-/// fail because `ctrlc::set_handler()` fails when there's already a handler.
+/// deleting the whole directory when the process is terminated (`SIGINT`, `SIGTERM`, `SIGHUP` or
+/// `SIGQUIT` on unix; Ctrl-C or a closed console on Windows). On Windows, initializing a second
+/// `TmpDirWrapper` panics because a second console handler is rejected; on unix, signal handlers
+/// can be registered more than once, so nothing stops a second one. It also listens for
+/// `SIGUSR1` and prints a one-line progress summary to stderr without terminating sort.
 /// The directory is only created once the first file is requested.
 pub struct TmpDirWrapper {
     temp_dir: Option<TempDir>,
     parent_path: PathBuf,
     size: usize,
     lock: Arc<Mutex<()>>,
+    status: Arc<Mutex<Status>>,
     signal_handler: Option<SignalHandler>,
+    progress_handler: Option<SignalHandler>,
+    compress_prog: Option<CompressProg>,
 }
 
 impl TmpDirWrapper {
@@ -87,10 +350,59 @@ impl TmpDirWrapper {
             size: 0,
             temp_dir: None,
             lock: Arc::default(),
+            status: Arc::default(),
             signal_handler: None,
+            progress_handler: None,
+            compress_prog: None,
         }
     }
 
+    /// Enable `--compress-program` for every temp file allocated from now on.
+    ///
+    /// Not yet wired up: the CLI needs a `--compress-program[=PROG]`/
+    /// `--compress-program=PROG --decompress-program=PROG` argument that calls this,
+    /// and the external-merge driver needs to call [`next_compressed_file`]/
+    /// [`open_compressed_file`] instead of [`next_file`]/plain `File` I/O wherever it
+    /// spills and re-reads chunks. Both live in `sort.rs`, which isn't part of this
+    /// snapshot (only `tmp_dir.rs` and `error.rs` are), so until that wiring lands, setting this has
+    /// no user-visible effect.
+    ///
+    /// [`next_file`]: Self::next_file
+    /// [`next_compressed_file`]: Self::next_compressed_file
+    /// [`open_compressed_file`]: Self::open_compressed_file
+    pub fn set_compress_prog(&mut self, compress_prog: CompressProg) {
+        self.compress_prog = Some(compress_prog);
+    }
+
+    /// Number of temp files allocated so far.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Record the current phase of the external merge, for `SIGUSR1` reporting.
+    ///
+    /// Not yet wired up: the external-merge driver in `sort.rs` needs to call this
+    /// as it moves from buffering input to merging spilled chunks. [`next_file`]/
+    /// [`next_compressed_file`] already keep `temp_files` equal to [`size`], but nothing
+    /// currently calls `set_phase` or [`record_spilled_bytes`]. Until that driver
+    /// code (not part of this snapshot) calls in, `SIGUSR1` will always report
+    /// "not yet started" and "0 byte(s) spilled".
+    ///
+    /// [`size`]: Self::size
+    /// [`next_file`]: Self::next_file
+    /// [`next_compressed_file`]: Self::next_compressed_file
+    /// [`record_spilled_bytes`]: Self::record_spilled_bytes
+    pub fn set_phase(&self, phase: MergePhase) {
+        self.status.lock().unwrap().phase = Some(phase);
+    }
+
+    /// Record that `bytes` more have been spilled to a temp file, for `SIGUSR1`
+    /// reporting. See [`set_phase`](Self::set_phase) for the same not-yet-wired-up
+    /// caveat: nothing in this snapshot calls this as chunks are actually spilled.
+    pub fn record_spilled_bytes(&self, bytes: u64) {
+        self.status.lock().unwrap().spilled_bytes += bytes;
+    }
+
     fn manual_trigger_fn(&self) -> Box<dyn Send + Sync + Fn()> {
         let path = self.temp_dir.as_ref().unwrap().path().to_owned();
         let lock = self.lock.clone();
@@ -105,6 +417,22 @@ impl TmpDirWrapper {
         })
     }
 
+    fn progress_trigger_fn(&self) -> Box<dyn Send + Sync + Fn()> {
+        let status = self.status.clone();
+        Box::new(move || {
+            let status = status.lock().unwrap();
+            show_error!(
+                "progress: {}, {} temp file(s), {} byte(s) spilled",
+                status
+                    .phase
+                    .map(MergePhase::as_str)
+                    .unwrap_or("not yet started"),
+                status.temp_files,
+                status.spilled_bytes,
+            );
+        })
+    }
+
     fn init_tmp_dir(&mut self) -> UResult<()> {
         assert!(self.temp_dir.is_none());
         assert_eq!(self.size, 0);
@@ -118,6 +446,8 @@ impl TmpDirWrapper {
         );
 
         self.signal_handler = Some(SignalHandler::install_signal_handler(self.manual_trigger_fn()).unwrap());
+        self.progress_handler =
+            Some(SignalHandler::install_progress_handler(self.progress_trigger_fn()).unwrap());
         // ctrlc::set_handler(move || {
         //     // Take the lock so that `next_file_path` returns no new file path,
         //     // and the program doesn't terminate before the handler has finished
@@ -139,6 +469,7 @@ impl TmpDirWrapper {
         let _lock = self.lock.lock().unwrap();
         let file_name = self.size.to_string();
         self.size += 1;
+        self.status.lock().unwrap().temp_files = self.size;
         let path = self.temp_dir.as_ref().unwrap().path().join(file_name);
         Ok((
             File::create(&path).map_err(|error| SortError::OpenTmpFileFailed { error })?,
@@ -146,6 +477,48 @@ impl TmpDirWrapper {
         ))
     }
 
+    /// Like [`next_file`](Self::next_file), but if `--compress-program` is set, the
+    /// returned writer pipes everything through that program before it reaches disk.
+    ///
+    /// Callers must call [`FinishableWrite::finish`] on the returned writer once
+    /// they're done with it, so a compressor that fails or exits non-zero after
+    /// accepting data is reported instead of silently producing a truncated temp
+    /// file that the later merge would otherwise read as valid sorted data.
+    pub fn next_compressed_file(&mut self) -> UResult<(Box<dyn FinishableWrite + Send>, PathBuf)> {
+        if self.temp_dir.is_none() {
+            self.init_tmp_dir()?;
+        }
+
+        let _lock = self.lock.lock().unwrap();
+        let file_name = self.size.to_string();
+        self.size += 1;
+        self.status.lock().unwrap().temp_files = self.size;
+        let path = self.temp_dir.as_ref().unwrap().path().join(file_name);
+
+        let writer: Box<dyn FinishableWrite + Send> = match &self.compress_prog {
+            Some(compress_prog) => {
+                let file =
+                    File::create(&path).map_err(|error| SortError::OpenTmpFileFailed { error })?;
+                Box::new(compress_prog.spawn_writer(file)?)
+            }
+            None => {
+                Box::new(File::create(&path).map_err(|error| SortError::OpenTmpFileFailed { error })?)
+            }
+        };
+        Ok((writer, path))
+    }
+
+    /// Open a temp file previously written via [`next_compressed_file`](Self::next_compressed_file),
+    /// transparently decompressing it if `--compress-program` is set.
+    pub fn open_compressed_file(&self, path: &Path) -> UResult<Box<dyn Read + Send>> {
+        match &self.compress_prog {
+            Some(compress_prog) => Ok(Box::new(compress_prog.spawn_reader(path)?)),
+            None => Ok(Box::new(
+                File::open(path).map_err(|error| SortError::OpenTmpFileFailed { error })?,
+            )),
+        }
+    }
+
     /// Function just waits if signal handler was called
     pub fn wait_if_signal(&self) {
         let _lock = self.lock.lock().unwrap();
@@ -154,7 +527,59 @@ impl TmpDirWrapper {
 
 /// Remove the directory at `path` by deleting its child files and then itself.
 /// Errors while deleting child files are ignored.
+///
+/// On unix, children are unlinked by bare name against a held directory file
+/// descriptor (`openat`/`unlinkat`) rather than by re-resolving a rebuilt path, so a
+/// symlink swapped into the directory between the scan and the delete can't be
+/// followed (the race behind CVE-2022-21658). Platforms without `*at` syscalls fall
+/// back to the path-based logic.
+#[cfg(unix)]
 fn remove_tmp_dir(path: &Path) -> std::io::Result<()> {
+    use nix::fcntl::{self, OFlag};
+    use nix::sys::stat::Mode;
+    use nix::unistd::{unlinkat, UnlinkatFlags};
+    use std::os::unix::io::AsRawFd;
+
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return remove_tmp_dir_by_path(path);
+    };
+
+    let Ok(parent_fd) = fcntl::open(parent, OFlag::O_DIRECTORY | OFlag::O_NOFOLLOW, Mode::empty())
+    else {
+        return remove_tmp_dir_by_path(path);
+    };
+    let Ok(mut dir) =
+        nix::dir::Dir::openat(parent_fd, name, OFlag::O_DIRECTORY | OFlag::O_NOFOLLOW, Mode::empty())
+    else {
+        let _ = nix::unistd::close(parent_fd);
+        return remove_tmp_dir_by_path(path);
+    };
+
+    let dir_fd = dir.as_raw_fd();
+    for entry in dir.iter().flatten() {
+        let entry_name = entry.file_name();
+        if entry_name.to_bytes() == b"." || entry_name.to_bytes() == b".." {
+            continue;
+        }
+        // if we fail to delete the file here it was probably deleted by another thread
+        // in the meantime, but that's ok.
+        let _ = unlinkat(Some(dir_fd), entry_name, UnlinkatFlags::NoRemoveDir);
+    }
+    drop(dir);
+
+    let result = unlinkat(Some(parent_fd), name, UnlinkatFlags::RemoveDir)
+        .map_err(std::io::Error::from);
+    let _ = nix::unistd::close(parent_fd);
+    result
+}
+
+#[cfg(not(unix))]
+fn remove_tmp_dir(path: &Path) -> std::io::Result<()> {
+    remove_tmp_dir_by_path(path)
+}
+
+/// Path-based removal used on platforms without `openat`/`unlinkat`.
+fn remove_tmp_dir_by_path(path: &Path) -> std::io::Result<()> {
     if let Ok(read_dir) = std::fs::read_dir(path) {
         for file in read_dir.flatten() {
             // if we fail to delete the file here it was probably deleted by another thread